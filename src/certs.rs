@@ -1,34 +1,254 @@
-use openssl::{
-    hash::MessageDigest,
-    nid::Nid,
-    pkey::{PKey, Private},
-    rsa::Rsa,
-    x509::{X509Name, X509Req, X509},
+use clap::clap_derive::ArgEnum;
+use rcgen::{
+    Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose as RcgenExtendedKeyUsagePurpose, KeyPair, KeyUsagePurpose, SanType,
+    PKCS_ECDSA_P256_SHA256, PKCS_RSA_SHA256,
 };
+use rsa::{pkcs8::EncodePrivateKey, RsaPrivateKey};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
 
+/// The key algorithm to use when generating the private key for a CSR.
+#[derive(Clone, Debug, ArgEnum)]
+pub(crate) enum KeyAlgorithm {
+    Rsa2048,
+    Rsa4096,
+    EcdsaP256,
+}
+
+/// An extended key usage purpose that may be embedded in a CSR.
+#[derive(Clone, Debug, ArgEnum)]
+pub(crate) enum ExtendedKeyUsagePurpose {
+    ClientAuth,
+    ServerAuth,
+}
+
+fn generate_key_pair(algorithm: &KeyAlgorithm) -> Result<KeyPair, Box<dyn std::error::Error>> {
+    match algorithm {
+        KeyAlgorithm::EcdsaP256 => Ok(KeyPair::generate(&PKCS_ECDSA_P256_SHA256)?),
+        KeyAlgorithm::Rsa2048 => rsa_key_pair(2048),
+        KeyAlgorithm::Rsa4096 => rsa_key_pair(4096),
+    }
+}
+
+fn rsa_key_pair(bits: usize) -> Result<KeyPair, Box<dyn std::error::Error>> {
+    let key = RsaPrivateKey::new(&mut rand::thread_rng(), bits)?;
+    let der = key.to_pkcs8_der()?;
+    Ok(KeyPair::from_der(der.as_bytes())?)
+}
+
+/// Create a CSR for the given common name.
+///
+/// `sans` may contain DNS names or IP addresses and are embedded as a
+/// Subject Alternative Name extension. The CSR always requests
+/// `DigitalSignature`+`KeyEncipherment` key usage and `ClientAuth` extended
+/// key usage, plus any additional purposes given in `eku`.
+///
+/// Returns the PEM-encoded private key and the PEM-encoded CSR.
 pub(crate) fn create_csr(
     common_name: &str,
-) -> Result<(PKey<Private>, X509Req), Box<dyn std::error::Error>> {
-    let rsa = Rsa::generate(2048)?;
-    let key = PKey::from_rsa(rsa)?;
+    sans: &[String],
+    key_algorithm: &KeyAlgorithm,
+    eku: &[ExtendedKeyUsagePurpose],
+) -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
+    let key_pair = generate_key_pair(key_algorithm)?;
+
+    let mut distinguished_name = DistinguishedName::new();
+    distinguished_name.push(DnType::CommonName, common_name);
+    distinguished_name.push(DnType::OrganizationName, "WirePact PKI");
+
+    let mut params = CertificateParams::default();
+    params.alg = match key_algorithm {
+        KeyAlgorithm::EcdsaP256 => &PKCS_ECDSA_P256_SHA256,
+        KeyAlgorithm::Rsa2048 | KeyAlgorithm::Rsa4096 => &PKCS_RSA_SHA256,
+    };
+    params.distinguished_name = distinguished_name;
+    params.key_usages = vec![
+        KeyUsagePurpose::DigitalSignature,
+        KeyUsagePurpose::KeyEncipherment,
+    ];
+
+    let mut extended_key_usages = vec![RcgenExtendedKeyUsagePurpose::ClientAuth];
+    for purpose in eku {
+        if let ExtendedKeyUsagePurpose::ServerAuth = purpose {
+            extended_key_usages.push(RcgenExtendedKeyUsagePurpose::ServerAuth);
+        }
+    }
+    params.extended_key_usages = extended_key_usages;
+
+    params.subject_alt_names = sans
+        .iter()
+        .map(|entry| match entry.parse::<IpAddr>() {
+            Ok(ip) => SanType::IpAddress(ip),
+            Err(_) => SanType::DnsName(entry.clone()),
+        })
+        .collect();
+
+    params.key_pair = Some(key_pair);
+
+    let cert = Certificate::from_params(params)?;
+    let csr_pem = cert.serialize_request_pem()?;
+    let key_pem = cert.get_key_pair().serialize_pem();
+
+    Ok((key_pem.into_bytes(), csr_pem.into_bytes()))
+}
+
+/// Check whether `pem` contains at least one parseable certificate.
+pub(crate) fn is_valid_certificate(pem: &[u8]) -> bool {
+    let mut reader = pem;
+    matches!(rustls_pemfile::certs(&mut reader), Ok(certs) if !certs.is_empty())
+}
+
+/// Check whether `pem` contains at least one parseable private key.
+pub(crate) fn is_valid_private_key(pem: &[u8]) -> bool {
+    let mut reader = pem;
+    rustls_pemfile::read_all(&mut reader)
+        .map(|items| {
+            items.iter().any(|item| {
+                matches!(
+                    item,
+                    rustls_pemfile::Item::PKCS8Key(_)
+                        | rustls_pemfile::Item::RSAKey(_)
+                        | rustls_pemfile::Item::ECKey(_)
+                )
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Return the `notAfter` timestamp (seconds since the Unix epoch) of the
+/// first certificate found in `pem`.
+pub(crate) fn certificate_not_after(pem: &[u8]) -> Result<i64, Box<dyn std::error::Error>> {
+    let mut reader = pem;
+    let certs = rustls_pemfile::certs(&mut reader)?;
+    let der = certs.first().ok_or("No certificate found in PEM data")?;
+    let (_, cert) = x509_parser::certificate::X509Certificate::from_der(der)?;
+
+    Ok(cert.validity().not_after.timestamp())
+}
+
+/// Bundle the private certificate, its key, and the CA into a
+/// password-protected PKCS#12 container.
+///
+/// `p12::PFX::new` only accepts a single additional certificate alongside
+/// the leaf, so only the home PKI CA (`Storage::get_ca`) is embedded here,
+/// not the full fetched chain (`Storage::store_chain`) — intermediate
+/// certificates beyond the CA are not representable in the resulting bundle.
+pub(crate) fn build_pkcs12(
+    certificate_pem: &[u8],
+    key_pem: &[u8],
+    ca_pem: &[u8],
+    password: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut certificate_reader = certificate_pem;
+    let certificate_der = rustls_pemfile::certs(&mut certificate_reader)?
+        .into_iter()
+        .next()
+        .ok_or("No certificate found in PEM data")?;
 
-    let mut req_builder = X509Req::builder()?;
-    req_builder.set_pubkey(key.as_ref())?;
-    req_builder.set_version(2)?;
-    let mut name = X509Name::builder()?;
-    name.append_entry_by_nid(Nid::COMMONNAME, common_name)?;
-    name.append_entry_by_nid(Nid::ORGANIZATIONNAME, "WirePact PKI")?;
-    let name = name.build();
-    req_builder.set_subject_name(name.as_ref())?;
-    req_builder.sign(key.as_ref(), MessageDigest::sha256())?;
+    let mut ca_reader = ca_pem;
+    let ca_der = rustls_pemfile::certs(&mut ca_reader)?
+        .into_iter()
+        .next()
+        .ok_or("No CA certificate found in PEM data")?;
 
-    let req = req_builder.build();
+    let mut key_reader = key_pem;
+    let key_der = rustls_pemfile::pkcs8_private_keys(&mut key_reader)?
+        .into_iter()
+        .next()
+        .ok_or("No private key found in PEM data")?;
 
-    Ok((key, req))
+    let pfx = p12::PFX::new(
+        &certificate_der,
+        &key_der,
+        Some(&ca_der),
+        password,
+        "wirepact-contract-provider",
+    )
+    .ok_or("Failed to build PKCS#12 bundle")?;
+
+    Ok(pfx.to_der())
 }
 
+/// Hash the DER encoding of the first certificate found in `public_key`
+/// (PEM) with SHA-256, matching the fingerprint previously computed by
+/// OpenSSL over the same bytes.
 pub(crate) fn certificate_hash(public_key: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
-    let cert = X509::from_pem(public_key)?;
-    let hash = cert.digest(MessageDigest::sha256())?;
-    Ok(hex::encode(hash))
+    let mut reader = public_key;
+    let certs = rustls_pemfile::certs(&mut reader)?;
+    let der = certs.first().ok_or("No certificate found in PEM data")?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(der);
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn self_signed_cert() -> (String, String) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        (cert.serialize_pem().unwrap(), cert.serialize_private_key_pem())
+    }
+
+    #[test]
+    fn is_valid_certificate_accepts_real_certificate() {
+        let (cert_pem, _) = self_signed_cert();
+        assert!(is_valid_certificate(cert_pem.as_bytes()));
+    }
+
+    #[test]
+    fn is_valid_certificate_rejects_garbage() {
+        assert!(!is_valid_certificate(b"not a certificate"));
+    }
+
+    #[test]
+    fn is_valid_private_key_accepts_real_key() {
+        let (_, key_pem) = self_signed_cert();
+        assert!(is_valid_private_key(key_pem.as_bytes()));
+    }
+
+    #[test]
+    fn is_valid_private_key_rejects_garbage() {
+        assert!(!is_valid_private_key(b"not a key"));
+    }
+
+    #[test]
+    fn certificate_not_after_parses_a_future_expiry() {
+        let (cert_pem, _) = self_signed_cert();
+        let not_after = certificate_not_after(cert_pem.as_bytes()).unwrap();
+        assert!(not_after > 0);
+    }
+
+    #[test]
+    fn certificate_hash_is_stable_for_the_same_certificate() {
+        let (cert_pem, _) = self_signed_cert();
+        let first = certificate_hash(cert_pem.as_bytes()).unwrap();
+        let second = certificate_hash(cert_pem.as_bytes()).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn build_pkcs12_bundles_cert_key_and_ca() {
+        let (cert_pem, key_pem) = self_signed_cert();
+        let (ca_pem, _) = self_signed_cert();
+        let bundle = build_pkcs12(cert_pem.as_bytes(), key_pem.as_bytes(), ca_pem.as_bytes(), "changeit")
+            .expect("a valid cert/key/ca triple must bundle into PKCS#12");
+        assert!(!bundle.is_empty());
+    }
+
+    #[test]
+    fn create_csr_succeeds_with_sans_and_additional_eku() {
+        let (key_pem, csr_pem) = create_csr(
+            "test-common-name",
+            &["example.com".to_string(), "10.0.0.1".to_string()],
+            &KeyAlgorithm::EcdsaP256,
+            &[ExtendedKeyUsagePurpose::ServerAuth],
+        )
+        .unwrap();
+
+        assert!(!key_pem.is_empty());
+        assert!(!csr_pem.is_empty());
+    }
 }
@@ -1,17 +1,22 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use clap::{clap_derive::ArgEnum, Parser};
 use grpc::pki::pki_service_client::PkiServiceClient;
 use log::{debug, error, info};
-use tonic::{transport::Endpoint, Request};
+use tonic::{
+    transport::{Certificate, ClientTlsConfig, Endpoint, Identity},
+    Request,
+};
 
 use crate::{
-    certs::create_csr,
+    certs::{
+        build_pkcs12, certificate_not_after, create_csr, ExtendedKeyUsagePurpose, KeyAlgorithm,
+    },
     grpc::contracts::{
         contracts_service_client::ContractsServiceClient,
         get_certificates_request::ParticipantIdentifier,
     },
-    storage::{kubernetes::KubernetesStorage, local::LocalStorage, Storage},
+    storage::{kubernetes::KubernetesStorage, local::LocalStorage, s3::S3Storage, Storage},
 };
 
 mod certs;
@@ -22,6 +27,7 @@ mod storage;
 pub(crate) enum StorageAdapter {
     Local,
     Kubernetes,
+    S3,
 }
 
 #[derive(Parser, Debug)]
@@ -29,10 +35,11 @@ pub(crate) enum StorageAdapter {
 struct Cli {
     /// The storage adapter to use.
     ///
-    /// Possible values: local, kubernetes
+    /// Possible values: local, kubernetes, s3
     ///
     /// Local will use local filesystem to store the certificate chain and private certificate&,
-    /// while kubernetes will use Kubernetes secrets.
+    /// kubernetes will use Kubernetes secrets, and s3 will use an S3-compatible
+    /// object storage bucket (e.g. AWS S3, MinIO, Garage).
     ///
     /// Defaults to "local".
     #[clap(arg_enum, short, long, env, default_value = "local")]
@@ -85,6 +92,86 @@ struct Cli {
     /// Defaults to none.
     #[clap(long, env)]
     fetch_interval: Option<String>,
+
+    /// Additional Subject Alternative Names (DNS names or IP addresses) to
+    /// request for the private certificate of this provider.
+    #[clap(long)]
+    san: Vec<String>,
+
+    /// The key algorithm to use for the private certificate of this provider.
+    ///
+    /// Possible values: rsa2048, rsa4096, ecdsa-p256
+    ///
+    /// Defaults to "rsa2048".
+    #[clap(arg_enum, long, env, default_value = "rsa2048")]
+    key_algorithm: KeyAlgorithm,
+
+    /// Additional extended key usage purposes to request for the private
+    /// certificate of this provider, on top of the always-present
+    /// `ClientAuth` purpose.
+    ///
+    /// Possible values: client-auth, server-auth
+    #[clap(arg_enum, long)]
+    eku: Vec<ExtendedKeyUsagePurpose>,
+
+    /// The endpoint of the S3-compatible object storage, in case of the s3
+    /// storage adapter. Required for the s3 storage adapter.
+    #[clap(long, env)]
+    s3_endpoint: Option<String>,
+
+    /// The bucket name in the S3-compatible object storage, in case of the
+    /// s3 storage adapter. Required for the s3 storage adapter.
+    #[clap(long, env)]
+    s3_bucket: Option<String>,
+
+    /// The key prefix under which objects are stored in the bucket, in case
+    /// of the s3 storage adapter.
+    /// Defaults to "wirepact-contracts".
+    #[clap(long, env, default_value = "wirepact-contracts")]
+    s3_prefix: String,
+
+    /// The region of the S3-compatible object storage, in case of the s3
+    /// storage adapter.
+    /// Defaults to "us-east-1".
+    #[clap(long, env, default_value = "us-east-1")]
+    s3_region: String,
+
+    /// The access key for the S3-compatible object storage, in case of the
+    /// s3 storage adapter. Required for the s3 storage adapter.
+    #[clap(long, env)]
+    s3_access_key: Option<String>,
+
+    /// The secret key for the S3-compatible object storage, in case of the
+    /// s3 storage adapter. Required for the s3 storage adapter.
+    #[clap(long, env)]
+    s3_secret_key: Option<String>,
+
+    /// The window before certificate expiry in which the private certificate
+    /// is proactively re-signed, e.g. "720h" for 30 days.
+    /// Defaults to "720h".
+    #[clap(long, env, default_value = "720h")]
+    renew_before: String,
+
+    /// Use mTLS for the connections to the PKI and contract repository,
+    /// pinning the server certificate chain to the stored home PKI CA and
+    /// presenting the provider's own signed certificate as client identity.
+    /// Has no effect while no CA or no valid, non-expiring private certificate
+    /// is stored: the initial bootstrap and every re-signing round happen in
+    /// plaintext.
+    #[clap(long, env)]
+    tls: bool,
+
+    /// When set together with `--tls`, do not pin the server certificate to
+    /// the home PKI CA and instead trust the system root certificates.
+    /// Only useful for testing; never use in production.
+    #[clap(long, env)]
+    insecure: bool,
+
+    /// If set, also bundle the private certificate, its key, and the CA into
+    /// a password-protected PKCS#12 container, encrypted with this password.
+    /// If omitted, no PKCS#12 bundle is produced.
+    #[clap(long, env)]
+    pkcs12_password: Option<String>,
 }
 
 #[tokio::main]
@@ -137,12 +224,131 @@ async fn provider_interval(config: Cli, duration: Duration) -> Result<(), Box<()
     }
 }
 
+/// Check whether the stored private certificate is missing, expired, or
+/// within `renew_before` of expiry and therefore needs to be re-signed.
+async fn certificate_needs_renewal(
+    storage: &dyn Storage,
+    renew_before: Duration,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if !storage.has_certificate().await {
+        return Ok(true);
+    }
+
+    let certificate = storage.get_certificate().await?;
+    let not_after = certificate_not_after(&certificate)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+    if not_after <= now {
+        info!("Private certificate is expired. Re-signing.");
+        return Ok(true);
+    }
+
+    let remaining = Duration::from_secs((not_after - now) as u64);
+    if remaining < renew_before {
+        info!(
+            "Private certificate expires in {}s, within the renewal window of {}s. Re-signing.",
+            remaining.as_secs(),
+            renew_before.as_secs()
+        );
+        return Ok(true);
+    }
+
+    debug!(
+        "Private certificate is valid for {}s more.",
+        remaining.as_secs()
+    );
+    Ok(false)
+}
+
+/// Build the mTLS config presenting the stored private certificate as
+/// client identity. Unless `insecure` is set, the server certificate is
+/// pinned to the stored home PKI CA instead of the system root certificates.
+async fn build_tls_config(
+    storage: &dyn Storage,
+    insecure: bool,
+) -> Result<ClientTlsConfig, Box<dyn std::error::Error>> {
+    let certificate = storage.get_certificate().await?;
+    let key = storage.get_private_key().await?;
+    let mut tls = ClientTlsConfig::new().identity(Identity::from_pem(certificate, key));
+
+    if insecure {
+        debug!("TLS pinning disabled (--insecure); trusting system root certificates.");
+    } else {
+        let (ca, _) = storage.get_ca().await?;
+        tls = tls.ca_certificate(Certificate::from_pem(ca));
+    }
+
+    Ok(tls)
+}
+
 async fn fetch_contracts(config: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     info!("Fetching contracts and certificates.");
 
-    let channel = Endpoint::from_shared(config.pki_address.to_string())?
-        .connect()
-        .await?;
+    let storage: Box<dyn Storage> = (match &config.storage {
+        StorageAdapter::Local => {
+            debug!("Using Local storage.");
+            let storage = LocalStorage::new().await?;
+            Ok(Box::new(storage) as Box<dyn Storage>)
+        }
+        StorageAdapter::Kubernetes => {
+            debug!("Using Kubernetes storage.");
+            let storage = KubernetesStorage::new(&config.secret_name).await?;
+            Ok(Box::new(storage) as Box<dyn Storage>)
+        }
+        StorageAdapter::S3 => {
+            debug!("Using S3 storage.");
+            let endpoint = config
+                .s3_endpoint
+                .as_deref()
+                .ok_or("s3_endpoint is required for the s3 storage adapter")?;
+            let bucket = config
+                .s3_bucket
+                .as_deref()
+                .ok_or("s3_bucket is required for the s3 storage adapter")?;
+            let access_key = config
+                .s3_access_key
+                .as_deref()
+                .ok_or("s3_access_key is required for the s3 storage adapter")?;
+            let secret_key = config
+                .s3_secret_key
+                .as_deref()
+                .ok_or("s3_secret_key is required for the s3 storage adapter")?;
+
+            let storage = S3Storage::new(
+                endpoint,
+                bucket,
+                &config.s3_prefix,
+                &config.s3_region,
+                access_key,
+                secret_key,
+            )
+            .await?;
+            Ok(Box::new(storage) as Box<dyn Storage>)
+        }
+    }
+        as Result<Box<dyn Storage>, Box<dyn std::error::Error>>)?;
+
+    let renew_before = parse_duration::parse(&config.renew_before)?;
+    let needs_new_certificate = certificate_needs_renewal(storage.as_ref(), renew_before).await?;
+
+    // A certificate that is absent, expired, or about to expire can't be
+    // trusted as client identity for the PKI connection that would renew
+    // it, so treat that case like "no certificate yet" and fall back to
+    // plaintext for this round instead of locking the provider out of its
+    // own renewal.
+    let use_tls = config.tls && storage.has_ca().await && !needs_new_certificate;
+    if config.tls && !use_tls {
+        info!(
+            "TLS requested but no usable (non-expiring) CA/certificate stored yet; bootstrapping over plaintext first."
+        );
+    }
+
+    let mut pki_endpoint = Endpoint::from_shared(config.pki_address.to_string())?;
+    if use_tls {
+        let tls_config = build_tls_config(storage.as_ref(), config.insecure).await?;
+        pki_endpoint = pki_endpoint.tls_config(tls_config)?;
+    }
+    let channel = pki_endpoint.connect().await?;
     let mut pki = PkiServiceClient::with_interceptor(channel, |mut request: Request<()>| {
         if let Some(key) = &config.pki_api_key {
             request
@@ -153,9 +359,12 @@ async fn fetch_contracts(config: &Cli) -> Result<(), Box<dyn std::error::Error>>
         Ok(request)
     });
 
-    let channel = Endpoint::from_shared(config.repo_address.to_string())?
-        .connect()
-        .await?;
+    let mut repo_endpoint = Endpoint::from_shared(config.repo_address.to_string())?;
+    if use_tls {
+        let tls_config = build_tls_config(storage.as_ref(), config.insecure).await?;
+        repo_endpoint = repo_endpoint.tls_config(tls_config)?;
+    }
+    let channel = repo_endpoint.connect().await?;
     let mut repo = ContractsServiceClient::with_interceptor(channel, |mut request: Request<()>| {
         if let Some(key) = &config.repo_api_key {
             request
@@ -165,20 +374,6 @@ async fn fetch_contracts(config: &Cli) -> Result<(), Box<dyn std::error::Error>>
         Ok(request)
     });
 
-    let storage: Box<dyn Storage> = (match &config.storage {
-        StorageAdapter::Local => {
-            debug!("Using Local storage.");
-            let storage = LocalStorage::new().await?;
-            Ok(Box::new(storage) as Box<dyn Storage>)
-        }
-        StorageAdapter::Kubernetes => {
-            debug!("Using Kubernetes storage.");
-            let storage = KubernetesStorage::new(&config.secret_name).await?;
-            Ok(Box::new(storage) as Box<dyn Storage>)
-        }
-    }
-        as Result<Box<dyn Storage>, Box<dyn std::error::Error>>)?;
-
     debug!("Check PKI public certificate.");
     if !storage.has_ca().await {
         info!("Fetching PKI public certificate.");
@@ -187,20 +382,34 @@ async fn fetch_contracts(config: &Cli) -> Result<(), Box<dyn std::error::Error>>
     }
 
     debug!("Check private certificate.");
-    if !storage.has_certificate().await {
+    if needs_new_certificate {
         info!("Sign private certificate.");
-        let (key, csr) = create_csr(&config.common_name)?;
+        let (key_pem, csr_pem) = create_csr(
+            &config.common_name,
+            &config.san,
+            &config.key_algorithm,
+            &config.eku,
+        )?;
         let response = pki
-            .sign_csr(Request::new(grpc::pki::SignCsrRequest {
-                csr: csr.to_pem()?,
-            }))
+            .sign_csr(Request::new(grpc::pki::SignCsrRequest { csr: csr_pem }))
             .await?
             .into_inner();
         storage
-            .store_certificate(&response.certificate, &key.private_key_to_pem_pkcs8()?)
+            .store_certificate(&response.certificate, &key_pem)
             .await?;
     }
 
+    if let Some(password) = &config.pkcs12_password {
+        if needs_new_certificate || !storage.has_pkcs12().await {
+            info!("Building PKCS#12 bundle for the private certificate.");
+            let certificate = storage.get_certificate().await?;
+            let key = storage.get_private_key().await?;
+            let (ca, _) = storage.get_ca().await?;
+            let bundle = build_pkcs12(&certificate, &key, &ca, password)?;
+            storage.store_pkcs12(&bundle).await?;
+        }
+    }
+
     debug!("Fetch certificate chain.");
     let (ca, ca_hash) = storage.get_ca().await?;
     let response = repo
@@ -240,3 +449,153 @@ async fn signal() {
 
     info!("Signal received. Shutting down.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{certificate_needs_renewal, Cli, Storage};
+    use rcgen::{Certificate, CertificateParams};
+
+    struct FakeStorage {
+        certificate: Option<Vec<u8>>,
+    }
+
+    #[tonic::async_trait]
+    impl Storage for FakeStorage {
+        async fn has_certificate(&self) -> bool {
+            self.certificate.is_some()
+        }
+
+        async fn get_certificate(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            self.certificate
+                .clone()
+                .ok_or_else(|| "no certificate stored".into())
+        }
+
+        async fn get_private_key(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            unimplemented!("not used by certificate_needs_renewal")
+        }
+
+        async fn store_certificate(
+            &self,
+            _certificate: &[u8],
+            _key: &[u8],
+        ) -> Result<(), Box<dyn std::error::Error>> {
+            unimplemented!("not used by certificate_needs_renewal")
+        }
+
+        async fn has_ca(&self) -> bool {
+            unimplemented!("not used by certificate_needs_renewal")
+        }
+
+        async fn get_ca(&self) -> Result<(Vec<u8>, String), Box<dyn std::error::Error>> {
+            unimplemented!("not used by certificate_needs_renewal")
+        }
+
+        async fn store_ca(&self, _certificate: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+            unimplemented!("not used by certificate_needs_renewal")
+        }
+
+        async fn store_chain(
+            &self,
+            _certificates: &Vec<Vec<u8>>,
+        ) -> Result<(), Box<dyn std::error::Error>> {
+            unimplemented!("not used by certificate_needs_renewal")
+        }
+
+        async fn has_pkcs12(&self) -> bool {
+            unimplemented!("not used by certificate_needs_renewal")
+        }
+
+        async fn store_pkcs12(&self, _bundle: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+            unimplemented!("not used by certificate_needs_renewal")
+        }
+    }
+
+    /// Build a self-signed certificate PEM valid from 2020-01-01 until the
+    /// given `not_after` date.
+    fn cert_expiring_on(year: i32, month: u8, day: u8) -> Vec<u8> {
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+        params.not_before = rcgen::date_time_ymd(2020, 1, 1);
+        params.not_after = rcgen::date_time_ymd(year, month, day);
+
+        let cert = Certificate::from_params(params).unwrap();
+        cert.serialize_pem().unwrap().into_bytes()
+    }
+
+    #[tokio::test]
+    async fn certificate_needs_renewal_is_true_when_no_certificate_is_stored() {
+        let storage = FakeStorage { certificate: None };
+
+        assert!(certificate_needs_renewal(&storage, std::time::Duration::from_secs(0))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn certificate_needs_renewal_is_true_for_an_expired_certificate() {
+        let storage = FakeStorage {
+            certificate: Some(cert_expiring_on(2025, 1, 1)),
+        };
+
+        assert!(certificate_needs_renewal(&storage, std::time::Duration::from_secs(60))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn certificate_needs_renewal_is_true_within_the_renewal_window() {
+        let storage = FakeStorage {
+            // Not yet expired, but well inside a renewal window of ten years.
+            certificate: Some(cert_expiring_on(2030, 1, 1)),
+        };
+
+        assert!(
+            certificate_needs_renewal(&storage, std::time::Duration::from_secs(10 * 365 * 24 * 60 * 60))
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn certificate_needs_renewal_is_false_when_comfortably_valid() {
+        let storage = FakeStorage {
+            certificate: Some(cert_expiring_on(2030, 1, 1)),
+        };
+
+        assert!(
+            !certificate_needs_renewal(&storage, std::time::Duration::from_secs(30 * 24 * 60 * 60))
+                .await
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn cli_parses_with_only_required_arguments() {
+        let cli = Cli::try_parse_from([
+            "k8s-contract-provider",
+            "--pki-address",
+            "http://pki.example.com",
+            "--repo-address",
+            "http://repo.example.com",
+        ])
+        .expect("default arguments (including --key-algorithm) must parse");
+
+        assert!(matches!(cli.key_algorithm, crate::certs::KeyAlgorithm::Rsa2048));
+    }
+
+    #[test]
+    fn cli_accepts_each_documented_key_algorithm_value() {
+        for value in ["rsa2048", "rsa4096", "ecdsa-p256"] {
+            Cli::try_parse_from([
+                "k8s-contract-provider",
+                "--pki-address",
+                "http://pki.example.com",
+                "--repo-address",
+                "http://repo.example.com",
+                "--key-algorithm",
+                value,
+            ])
+            .unwrap_or_else(|e| panic!("--key-algorithm {value} should parse: {e}"));
+        }
+    }
+}
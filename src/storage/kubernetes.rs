@@ -2,10 +2,9 @@ use std::{collections::BTreeMap, env, path::Path};
 
 use k8s_openapi::{api::core::v1::Secret, ByteString};
 use kube::{api::PostParams, config::Kubeconfig, Api, Client};
-use openssl::{pkey::PKey, x509::X509};
 use tokio::fs::read_to_string;
 
-use crate::certs::certificate_hash;
+use crate::certs::{certificate_hash, is_valid_certificate, is_valid_private_key};
 
 use super::Storage;
 
@@ -18,6 +17,7 @@ const SECRET_CERT_WITH_CA: &str = "cert_with_ca";
 const SECRET_KEY: &str = "key";
 const SECRET_CHAIN: &str = "chain";
 const SECRET_CA: &str = "ca";
+const SECRET_PKCS12: &str = "pkcs12";
 
 pub(crate) struct KubernetesStorage {
     secrets_api: Api<Secret>,
@@ -97,7 +97,7 @@ impl Storage for KubernetesStorage {
             if let Some(data) = secret.data {
                 let cert_ok = {
                     if let Some(cert) = data.get(SECRET_CERT) {
-                        X509::from_pem(&cert.0).is_ok()
+                        is_valid_certificate(&cert.0)
                     } else {
                         false
                     }
@@ -105,7 +105,7 @@ impl Storage for KubernetesStorage {
 
                 let key_ok = {
                     if let Some(key) = data.get(SECRET_KEY) {
-                        PKey::private_key_from_pem(&key.0).is_ok()
+                        is_valid_private_key(&key.0)
                     } else {
                         false
                     }
@@ -118,6 +118,30 @@ impl Storage for KubernetesStorage {
         false
     }
 
+    async fn get_certificate(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if let Ok(secret) = self.secrets_api.get(&self.secret_name).await {
+            if let Some(data) = secret.data {
+                if let Some(cert) = data.get(SECRET_CERT) {
+                    return Ok(cert.0.clone());
+                }
+            }
+        }
+
+        Err("No certificate found".into())
+    }
+
+    async fn get_private_key(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if let Ok(secret) = self.secrets_api.get(&self.secret_name).await {
+            if let Some(data) = secret.data {
+                if let Some(key) = data.get(SECRET_KEY) {
+                    return Ok(key.0.clone());
+                }
+            }
+        }
+
+        Err("No private key found".into())
+    }
+
     async fn store_certificate(
         &self,
         certificate: &[u8],
@@ -145,7 +169,7 @@ impl Storage for KubernetesStorage {
         if let Ok(secret) = self.secrets_api.get(&self.secret_name).await {
             if let Some(data) = secret.data {
                 if let Some(cert) = data.get(SECRET_CA) {
-                    return X509::from_pem(&cert.0).is_ok();
+                    return is_valid_certificate(&cert.0);
                 } else {
                     return false;
                 }
@@ -198,4 +222,24 @@ impl Storage for KubernetesStorage {
 
         Ok(())
     }
+
+    async fn has_pkcs12(&self) -> bool {
+        if let Ok(Some(secret)) = self.secrets_api.get_opt(&self.secret_name).await {
+            if let Some(data) = secret.data {
+                return data.contains_key(SECRET_PKCS12);
+            }
+        }
+
+        false
+    }
+
+    async fn store_pkcs12(&self, bundle: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.modify_secret(|secret| {
+            let data = secret.data.get_or_insert_with(BTreeMap::default);
+            data.insert(SECRET_PKCS12.to_string(), ByteString(bundle.to_vec()));
+        })
+        .await?;
+
+        Ok(())
+    }
 }
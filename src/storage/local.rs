@@ -1,13 +1,12 @@
 use std::path::Path;
 
 use log::debug;
-use openssl::{pkey::PKey, x509::X509};
 use tokio::{
     fs::{create_dir_all, read, write, File},
     io::AsyncWriteExt,
 };
 
-use crate::certs::certificate_hash;
+use crate::certs::{certificate_hash, is_valid_certificate, is_valid_private_key};
 
 use super::Storage;
 
@@ -28,10 +27,25 @@ impl Storage for LocalStorage {
     async fn has_certificate(&self) -> bool {
         let cert = Path::new(LOCAL_DATA_PATH).join("cert.crt");
         let key = Path::new(LOCAL_DATA_PATH).join("cert.key");
-        let cert_ok = X509::from_pem(&read(&cert).await.unwrap()).is_ok();
-        let key_ok = PKey::private_key_from_pem(&read(&key).await.unwrap()).is_ok();
 
-        cert.exists() && key.exists() && cert_ok && key_ok
+        if !cert.exists() || !key.exists() {
+            return false;
+        }
+
+        let cert_ok = is_valid_certificate(&read(&cert).await.unwrap());
+        let key_ok = is_valid_private_key(&read(&key).await.unwrap());
+
+        cert_ok && key_ok
+    }
+
+    async fn get_certificate(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let cert = Path::new(LOCAL_DATA_PATH).join("cert.crt");
+        Ok(read(cert).await?)
+    }
+
+    async fn get_private_key(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let key = Path::new(LOCAL_DATA_PATH).join("cert.key");
+        Ok(read(key).await?)
     }
 
     async fn store_certificate(
@@ -50,7 +64,7 @@ impl Storage for LocalStorage {
     async fn has_ca(&self) -> bool {
         let ca = Path::new(LOCAL_DATA_PATH).join("ca.crt");
 
-        ca.exists() && X509::from_pem(&read(ca).await.unwrap()).is_ok()
+        ca.exists() && is_valid_certificate(&read(ca).await.unwrap())
     }
 
     async fn get_ca(&self) -> Result<(Vec<u8>, String), Box<dyn std::error::Error>> {
@@ -81,4 +95,15 @@ impl Storage for LocalStorage {
 
         Ok(())
     }
+
+    async fn has_pkcs12(&self) -> bool {
+        Path::new(LOCAL_DATA_PATH).join("cert.p12").exists()
+    }
+
+    async fn store_pkcs12(&self, bundle: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let pkcs12_path = Path::new(LOCAL_DATA_PATH).join("cert.p12");
+        write(pkcs12_path, bundle).await?;
+
+        Ok(())
+    }
 }
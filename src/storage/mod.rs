@@ -1,11 +1,18 @@
 pub(crate) mod kubernetes;
 pub(crate) mod local;
+pub(crate) mod s3;
 
 #[tonic::async_trait]
 pub(crate) trait Storage: Send + Sync {
     /// Check if a private certificate is stored.
     async fn has_certificate(&self) -> bool;
 
+    /// Fetch the stored private certificate (PEM, without the key).
+    async fn get_certificate(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+
+    /// Fetch the stored private key (PEM) belonging to the private certificate.
+    async fn get_private_key(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+
     /// Store a private certificate.
     async fn store_certificate(
         &self,
@@ -27,4 +34,10 @@ pub(crate) trait Storage: Send + Sync {
         &self,
         certificates: &Vec<Vec<u8>>,
     ) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Check if a PKCS#12 bundle is stored.
+    async fn has_pkcs12(&self) -> bool;
+
+    /// Store a password-protected PKCS#12 bundle of the private certificate.
+    async fn store_pkcs12(&self, bundle: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
 }
@@ -0,0 +1,189 @@
+use aws_sdk_s3::{Client, Config, Credentials, Endpoint, Region};
+use http::Uri;
+
+use crate::certs::{certificate_hash, is_valid_certificate, is_valid_private_key};
+
+use super::Storage;
+
+const OBJECT_CA: &str = "ca";
+const OBJECT_CHAIN: &str = "chain";
+const OBJECT_KEY: &str = "key";
+const OBJECT_CERT: &str = "cert";
+const OBJECT_CERT_WITH_CA: &str = "cert_with_ca";
+const OBJECT_PKCS12: &str = "pkcs12";
+
+pub(crate) struct S3Storage {
+    client: Client,
+    bucket: String,
+    prefix: String,
+}
+
+impl S3Storage {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn new(
+        endpoint: &str,
+        bucket: &str,
+        prefix: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let credentials = Credentials::new(access_key, secret_key, None, None, "cli");
+        let config = Config::builder()
+            .endpoint_resolver(Endpoint::immutable(endpoint.parse::<Uri>()?))
+            .region(Region::new(region.to_string()))
+            .credentials_provider(credentials)
+            .build();
+
+        Ok(Self {
+            client: Client::from_conf(config),
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn object_key(&self, name: &str) -> String {
+        format!("{}/{}", self.prefix.trim_end_matches('/'), name)
+    }
+
+    async fn exists(&self, name: &str) -> bool {
+        self.client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(name))
+            .send()
+            .await
+            .is_ok()
+    }
+
+    async fn get_object(&self, name: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(name))
+            .send()
+            .await?;
+
+        Ok(object.body.collect().await?.into_bytes().to_vec())
+    }
+
+    async fn put_object(
+        &self,
+        name: &str,
+        data: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(name))
+            .body(data.to_vec().into())
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl Storage for S3Storage {
+    async fn has_certificate(&self) -> bool {
+        let cert_ok = matches!(self.get_object(OBJECT_CERT).await, Ok(cert) if is_valid_certificate(&cert));
+        let key_ok = matches!(self.get_object(OBJECT_KEY).await, Ok(key) if is_valid_private_key(&key));
+
+        cert_ok && key_ok
+    }
+
+    async fn get_certificate(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.get_object(OBJECT_CERT).await
+    }
+
+    async fn get_private_key(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.get_object(OBJECT_KEY).await
+    }
+
+    async fn store_certificate(
+        &self,
+        certificate: &[u8],
+        key: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (mut ca, _) = self.get_ca().await?;
+
+        self.put_object(OBJECT_CERT, certificate).await?;
+        self.put_object(OBJECT_KEY, key).await?;
+
+        let mut total = certificate.to_vec();
+        total.append(&mut ca);
+        self.put_object(OBJECT_CERT_WITH_CA, &total).await?;
+
+        Ok(())
+    }
+
+    async fn has_ca(&self) -> bool {
+        matches!(self.get_object(OBJECT_CA).await, Ok(ca) if is_valid_certificate(&ca))
+    }
+
+    async fn get_ca(&self) -> Result<(Vec<u8>, String), Box<dyn std::error::Error>> {
+        let ca = self.get_object(OBJECT_CA).await?;
+        let hash = certificate_hash(&ca)?;
+
+        Ok((ca, hash))
+    }
+
+    async fn store_ca(&self, certificate: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.put_object(OBJECT_CA, certificate).await
+    }
+
+    async fn store_chain(
+        &self,
+        certificates: &Vec<Vec<u8>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut certs: Vec<u8> = Vec::new();
+        for cert in certificates {
+            certs.extend_from_slice(cert);
+        }
+
+        self.put_object(OBJECT_CHAIN, &certs).await
+    }
+
+    async fn has_pkcs12(&self) -> bool {
+        self.exists(OBJECT_PKCS12).await
+    }
+
+    async fn store_pkcs12(&self, bundle: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.put_object(OBJECT_PKCS12, bundle).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_storage(prefix: &str) -> S3Storage {
+        let config = Config::builder()
+            .endpoint_resolver(Endpoint::immutable(
+                "http://localhost:9000".parse::<Uri>().unwrap(),
+            ))
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .build();
+
+        S3Storage {
+            client: Client::from_conf(config),
+            bucket: "test-bucket".to_string(),
+            prefix: prefix.to_string(),
+        }
+    }
+
+    #[test]
+    fn object_key_joins_prefix_and_name() {
+        let storage = test_storage("wirepact-contracts");
+        assert_eq!(storage.object_key("cert"), "wirepact-contracts/cert");
+    }
+
+    #[test]
+    fn object_key_trims_a_trailing_slash_on_the_prefix() {
+        let storage = test_storage("wirepact-contracts/");
+        assert_eq!(storage.object_key("cert"), "wirepact-contracts/cert");
+    }
+}